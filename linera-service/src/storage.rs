@@ -9,7 +9,11 @@ use linera_storage::MemoryStoreClient;
 use std::str::FromStr;
 
 #[cfg(feature = "rocksdb")]
-use {linera_storage::RocksdbStoreClient, std::path::PathBuf};
+use {
+    crate::storage_paths::{check_existing_store_format, StorageLayout},
+    linera_storage::RocksdbStoreClient,
+    std::path::PathBuf,
+};
 
 #[cfg(feature = "aws")]
 use {
@@ -91,16 +95,24 @@ impl StorageConfig {
             }
             #[cfg(feature = "rocksdb")]
             Rocksdb { path } => {
-                let is_new_dir = if path.is_dir() {
+                let layout = StorageLayout::create_or_open(path.clone())?;
+                // Acquired before any mutation beyond creating `path` itself, so two processes
+                // racing to open the same (possibly pre-layout) directory can't both decide it
+                // needs migrating and run the move concurrently. Held for the remainder of this
+                // arm so no other process can open `path` while we're using it.
+                let _lock = layout.lock()?;
+
+                layout.initialize(config)?;
+
+                let is_new_store = !layout.is_existing_store();
+                if !is_new_store {
                     tracing::warn!("Using existing database {:?}", path);
-                    false
-                } else {
-                    std::fs::create_dir_all(path)?;
-                    true
-                };
+                }
+                layout.check_or_write_metadata(config)?;
 
-                let mut client = RocksdbStoreClient::new(path.clone(), wasm_runtime, cache_size);
-                if is_new_dir {
+                let mut client =
+                    RocksdbStoreClient::new(layout.data_dir(), wasm_runtime, cache_size);
+                if is_new_store {
                     config.initialize_store(&mut client).await?;
                 }
                 job.run(client).await
@@ -147,9 +159,9 @@ impl FromStr for StorageConfig {
         }
         #[cfg(feature = "rocksdb")]
         if let Some(s) = input.strip_prefix(ROCKSDB) {
-            return Ok(Self::Rocksdb {
-                path: s.to_string().into(),
-            });
+            let path: PathBuf = s.to_string().into();
+            check_existing_store_format(&path)?;
+            return Ok(Self::Rocksdb { path });
         }
         #[cfg(feature = "aws")]
         if let Some(s) = input.strip_prefix(DYNAMO_DB) {
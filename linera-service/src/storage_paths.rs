@@ -0,0 +1,285 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The on-disk directory layout used by the RocksDB storage backend, plus the exclusive lock
+//! that keeps two processes from opening the same database directory at once.
+
+use crate::config::GenesisConfig;
+use anyhow::{bail, format_err};
+use fs2::FileExt;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+/// The current on-disk layout version. Bump this whenever the directory structure or metadata
+/// format changes in a way that's incompatible with older versions.
+pub const STORE_FORMAT_VERSION: u32 = 1;
+
+const DATA_DIR_NAME: &str = "data";
+const LOCK_FILE_NAME: &str = "LOCK";
+const METADATA_FILE_NAME: &str = "METADATA";
+/// File RocksDB itself creates at the root of a database directory; used to recognize a
+/// pre-existing flat store (one opened directly at `root` by code predating this layout).
+const ROCKSDB_MARKER_FILE_NAME: &str = "CURRENT";
+
+/// The versioned directory layout rooted at a single RocksDB storage path.
+///
+/// ```text
+/// <root>/
+///   LOCK      -- advisory exclusive lock, held for as long as the store is open
+///   METADATA  -- genesis fingerprint + STORE_FORMAT_VERSION, written on first use
+///   data/     -- the actual RocksDB database
+/// ```
+#[derive(Clone, Debug)]
+pub struct StorageLayout {
+    root: PathBuf,
+}
+
+impl StorageLayout {
+    /// Returns the layout rooted at `root`, creating `root` itself if it doesn't exist yet.
+    ///
+    /// This deliberately does *not* yet create `root/data` or migrate a pre-existing flat store
+    /// into it — that's [`Self::initialize`]'s job, and it must only run after the lock returned
+    /// by [`Self::lock`] is held. Otherwise two processes racing to open the same legacy
+    /// directory could both decide it needs migrating and run the move concurrently.
+    pub fn create_or_open(root: PathBuf) -> Result<Self, anyhow::Error> {
+        fs::create_dir_all(&root)?;
+        Ok(StorageLayout { root })
+    }
+
+    /// Prepares `root/data` for use: migrates a pre-existing flat RocksDB database at `root` into
+    /// it if one is found, then creates `root/data` if it still doesn't exist.
+    ///
+    /// Must only be called while holding the lock returned by [`Self::lock`], since migration
+    /// mutates `root` in place. If a migration happens, metadata is written immediately (using
+    /// `genesis_config`) so that [`Self::is_existing_store`] reports this as a pre-existing store
+    /// right away — otherwise the caller would see no metadata yet, conclude this is a brand new
+    /// store, and initialize (and potentially overwrite) the chain state that was just migrated.
+    pub fn initialize(&self, genesis_config: &GenesisConfig) -> Result<(), anyhow::Error> {
+        let data_dir = self.data_dir();
+        if self.root.join(ROCKSDB_MARKER_FILE_NAME).is_file()
+            && !data_dir.is_dir()
+            && !self.metadata_path().is_file()
+        {
+            migrate_flat_store_into_data_dir(&self.root, &data_dir)?;
+            self.check_or_write_metadata(genesis_config)?;
+        }
+        fs::create_dir_all(&data_dir)?;
+        Ok(())
+    }
+
+    /// The subdirectory holding the actual RocksDB database files.
+    pub fn data_dir(&self) -> PathBuf {
+        self.root.join(DATA_DIR_NAME)
+    }
+
+    fn lock_path(&self) -> PathBuf {
+        self.root.join(LOCK_FILE_NAME)
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        self.root.join(METADATA_FILE_NAME)
+    }
+
+    /// Returns whether this layout already has recorded metadata, i.e. whether this is a
+    /// pre-existing store rather than one just created by [`Self::create_or_open`].
+    pub fn is_existing_store(&self) -> bool {
+        self.metadata_path().is_file()
+    }
+
+    /// Acquires the exclusive lock for this layout, failing with a clear "database already in
+    /// use" error if another process already holds it.
+    pub fn lock(&self) -> Result<StorageLock, anyhow::Error> {
+        StorageLock::acquire(self.lock_path())
+    }
+
+    /// Validates this store's recorded metadata against `genesis_config`, or writes fresh
+    /// metadata if this is a newly created store.
+    pub fn check_or_write_metadata(&self, genesis_config: &GenesisConfig) -> Result<(), anyhow::Error> {
+        let genesis_fingerprint = genesis_fingerprint(genesis_config)?;
+
+        if let Some(metadata) = read_metadata(&self.metadata_path())? {
+            check_format_version(&self.root, metadata.format_version)?;
+            if metadata.genesis_fingerprint != genesis_fingerprint {
+                bail!(
+                    "Storage at {:?} was initialized with a different genesis configuration; \
+                    refusing to reuse it for a different network",
+                    self.root
+                );
+            }
+            return Ok(());
+        }
+
+        let metadata = StorageMetadata {
+            format_version: STORE_FORMAT_VERSION,
+            genesis_fingerprint,
+        };
+        fs::write(self.metadata_path(), serde_json::to_vec_pretty(&metadata)?)?;
+        Ok(())
+    }
+}
+
+/// Checks the format version recorded for an existing storage directory at `root`, without
+/// otherwise touching the directory (no creation, no locking).
+///
+/// Used by [`crate::storage::StorageConfig::from_str`] to reject an incompatible directory as
+/// early as argument parsing, before any genesis configuration is even available.
+pub fn check_existing_store_format(root: &Path) -> Result<(), anyhow::Error> {
+    let metadata_path = root.join(METADATA_FILE_NAME);
+    if let Some(metadata) = read_metadata(&metadata_path)? {
+        check_format_version(root, metadata.format_version)?;
+    }
+    Ok(())
+}
+
+/// Moves the contents of a pre-existing flat RocksDB directory at `root` into `data_dir`, so that
+/// an operator upgrading to the new layout doesn't lose their chain state. The lock file stays at
+/// `root`, so the lock held by the caller keeps guarding the directory. Stages the move
+/// through a temporary directory so a crash partway through doesn't leave `root` in a state where
+/// some files have moved and others haven't.
+fn migrate_flat_store_into_data_dir(root: &Path, data_dir: &Path) -> Result<(), anyhow::Error> {
+    tracing::warn!(
+        "Migrating pre-existing RocksDB database at {root:?} into the new {data_dir:?} layout",
+    );
+
+    let staging_dir = root.join(format!("{DATA_DIR_NAME}.migrating"));
+    fs::create_dir_all(&staging_dir).map_err(|error| {
+        format_err!("Failed to create staging directory {staging_dir:?} for migration: {error}")
+    })?;
+
+    for entry in fs::read_dir(root)
+        .map_err(|error| format_err!("Failed to read storage directory {root:?}: {error}"))?
+    {
+        let entry = entry?;
+        if entry.path() == staging_dir || entry.file_name() == LOCK_FILE_NAME {
+            continue;
+        }
+        let destination = staging_dir.join(entry.file_name());
+        fs::rename(entry.path(), &destination).map_err(|error| {
+            format_err!(
+                "Failed to migrate {:?} to {:?}: {error}",
+                entry.path(),
+                destination
+            )
+        })?;
+    }
+
+    fs::rename(&staging_dir, data_dir).map_err(|error| {
+        format_err!("Failed to finalize migration of {staging_dir:?} to {data_dir:?}: {error}")
+    })?;
+
+    Ok(())
+}
+
+fn check_format_version(root: &Path, format_version: u32) -> Result<(), anyhow::Error> {
+    if format_version != STORE_FORMAT_VERSION {
+        bail!(
+            "Storage at {root:?} was created with store format version {format_version}, \
+            but this binary uses version {STORE_FORMAT_VERSION}"
+        );
+    }
+    Ok(())
+}
+
+fn read_metadata(path: &Path) -> Result<Option<StorageMetadata>, anyhow::Error> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let contents = fs::read(path)
+        .map_err(|error| format_err!("Failed to read storage metadata at {path:?}: {error}"))?;
+    let metadata = serde_json::from_slice(&contents).map_err(|error| {
+        format_err!("Storage metadata at {path:?} is corrupt or from an incompatible version: {error}")
+    })?;
+    Ok(Some(metadata))
+}
+
+/// Metadata recorded alongside a RocksDB store, checked on every subsequent open.
+#[derive(Debug, Serialize, Deserialize)]
+struct StorageMetadata {
+    format_version: u32,
+    genesis_fingerprint: String,
+}
+
+/// Derives a fingerprint from a [`GenesisConfig`], used to detect accidental reuse of a storage
+/// directory across different networks.
+///
+/// This hashes the BCS encoding of `genesis_config` rather than its `Debug` output: `Debug` isn't
+/// a stable, versioned format, so a derive-macro bump or an unrelated field reordering would
+/// silently change the fingerprint and make every existing store falsely "incompatible".
+fn genesis_fingerprint(genesis_config: &GenesisConfig) -> Result<String, anyhow::Error> {
+    use sha3::{Digest, Sha3_256};
+    use std::fmt::Write;
+
+    let bytes = bcs::to_bytes(genesis_config)
+        .map_err(|error| format_err!("Failed to serialize genesis configuration: {error}"))?;
+    let digest = Sha3_256::digest(&bytes);
+
+    let mut fingerprint = String::with_capacity(digest.len() * 2);
+    for byte in digest {
+        write!(fingerprint, "{byte:02x}").expect("writing to a String cannot fail");
+    }
+    Ok(fingerprint)
+}
+
+/// An advisory exclusive lock on a storage directory, released when dropped.
+pub struct StorageLock {
+    file: File,
+}
+
+impl StorageLock {
+    fn acquire(path: PathBuf) -> Result<Self, anyhow::Error> {
+        let file = File::create(&path)
+            .map_err(|error| format_err!("Failed to open lock file {path:?}: {error}"))?;
+
+        file.try_lock_exclusive().map_err(|_| {
+            format_err!(
+                "Database at {:?} is already in use by another process",
+                path.parent().unwrap_or(&path)
+            )
+        })?;
+
+        Ok(StorageLock { file })
+    }
+}
+
+impl Drop for StorageLock {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
+
+#[test]
+fn test_create_or_open_does_not_touch_data_dir() {
+    // `create_or_open` only needs to exist for `lock()` to be callable (the lock file must have
+    // a parent directory); the data directory and any migration are `initialize`'s job, which
+    // must wait until the lock is actually held. Exercised directly rather than through
+    // `initialize`, since that also requires a `GenesisConfig` to fingerprint.
+    let root = tempfile::tempdir().unwrap();
+
+    let layout = StorageLayout::create_or_open(root.path().to_path_buf()).unwrap();
+
+    assert!(root.path().is_dir());
+    assert!(!layout.data_dir().is_dir());
+}
+
+#[test]
+fn test_migrate_flat_store_into_data_dir_moves_existing_files() {
+    let root = tempfile::tempdir().unwrap();
+    let data_dir = root.path().join(DATA_DIR_NAME);
+
+    // Simulate a store created by code that predates this layout: RocksDB files directly
+    // under `root`, with no `data/` subdirectory and no `METADATA` file.
+    fs::write(root.path().join(ROCKSDB_MARKER_FILE_NAME), b"1").unwrap();
+    fs::write(root.path().join("000001.sst"), b"pretend-chain-state").unwrap();
+
+    migrate_flat_store_into_data_dir(root.path(), &data_dir).unwrap();
+
+    assert!(data_dir.join(ROCKSDB_MARKER_FILE_NAME).is_file());
+    assert_eq!(
+        fs::read(data_dir.join("000001.sst")).unwrap(),
+        b"pretend-chain-state"
+    );
+    assert!(!root.path().join(ROCKSDB_MARKER_FILE_NAME).is_file());
+}
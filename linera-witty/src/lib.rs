@@ -0,0 +1,18 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! WIT (WebAssembly Interface Type) value marshalling and guest-memory access, usable with or
+//! without the standard library.
+//!
+//! By default this crate only needs `alloc`; enable the `std` feature for the handful of helpers
+//! that genuinely need an OS (none of which are required just to convert values to and from their
+//! WIT representation).
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+pub mod runtime;
+pub mod type_traits;
+
+pub use type_traits::{Layout, WitType};
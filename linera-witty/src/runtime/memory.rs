@@ -3,16 +3,32 @@
 
 //! Abstraction over how different runtimes manipulate the guest WebAssembly module's memory.
 
+#[cfg(feature = "std")]
+use super::pooling_allocator::{PooledSlotHandle, PoolingAllocator};
 use super::{InstanceWithFunction, Runtime, RuntimeError};
 use crate::{Layout, WitType};
 use frunk::{hlist, hlist_pat, HList};
+#[cfg(feature = "std")]
 use std::borrow::Cow;
 
+#[cfg(not(feature = "std"))]
+use alloc::borrow::Cow;
+
 /// An address for a location in a guest WebAssembly module's memory.
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct GuestPointer(u32);
 
 impl GuestPointer {
+    /// Creates a new address pointing at `offset` in the guest's address space.
+    pub(crate) fn new(offset: u32) -> Self {
+        GuestPointer(offset)
+    }
+
+    /// Returns this address as a raw offset into the guest's address space.
+    pub(crate) fn as_u32(&self) -> u32 {
+        self.0
+    }
+
     /// Returns a new address that's the current address advanced to after the size of `T`.
     pub fn after<T: WitType>(&self) -> Self {
         GuestPointer(self.0 + T::SIZE)
@@ -81,6 +97,52 @@ where
         <Instance as InstanceWithFunction<HList![i32, i32, i32, i32], HList![i32]>>::Function,
     >,
     cabi_free: Option<<Instance as InstanceWithFunction<HList![i32], HList![]>>::Function>,
+    /// A slot borrowed from a [`PoolingAllocator`], if this handle was created with
+    /// [`Memory::new_pooled`]. When present, [`Memory::allocate`]/[`Memory::deallocate`] are
+    /// served from it directly instead of calling into the guest's `cabi_realloc`/`cabi_free`.
+    ///
+    /// [`PoolingAllocator`] relies on `mmap` and a `std::sync::Mutex`, so this is only available
+    /// with the `std` feature enabled.
+    #[cfg(feature = "std")]
+    pooled_slot: Option<PooledSlotHandle<'runtime>>,
+}
+
+impl<'runtime, Instance> Memory<'runtime, Instance>
+where
+    Instance: CabiReallocAlias + CabiFreeAlias,
+{
+    /// Creates a handle that allocates guest memory on demand by calling into the module's own
+    /// `cabi_realloc`/`cabi_free` exports, as the runtime has always done.
+    pub fn new(instance: &'runtime mut Instance, memory: <Instance::Runtime as Runtime>::Memory) -> Self {
+        Memory {
+            instance,
+            memory,
+            cabi_realloc: None,
+            cabi_free: None,
+            #[cfg(feature = "std")]
+            pooled_slot: None,
+        }
+    }
+
+    /// Creates a handle backed by a slot borrowed from `pool`.
+    ///
+    /// [`Memory::allocate`]/[`Memory::deallocate`] then operate over that pooled slot
+    /// transparently, reusing its pre-reserved memory instead of instantiating the guest's
+    /// allocator on every call. The slot is returned to `pool` when the handle is dropped.
+    #[cfg(feature = "std")]
+    pub fn new_pooled(
+        instance: &'runtime mut Instance,
+        memory: <Instance::Runtime as Runtime>::Memory,
+        pool: &'runtime PoolingAllocator,
+    ) -> Result<Self, RuntimeError> {
+        Ok(Memory {
+            instance,
+            memory,
+            cabi_realloc: None,
+            cabi_free: None,
+            pooled_slot: Some(pool.allocate_handle()?),
+        })
+    }
 }
 
 impl<Instance> Memory<'_, Instance>
@@ -105,6 +167,11 @@ where
     /// Calls the guest module to allocate the memory, so the resulting allocation is managed by
     /// the guest.
     pub fn allocate(&mut self, size: u32) -> Result<GuestPointer, RuntimeError> {
+        #[cfg(feature = "std")]
+        if let Some(handle) = &mut self.pooled_slot {
+            return handle.slot_mut().allocate(size);
+        }
+
         if self.cabi_realloc.is_none() {
             self.cabi_realloc = Some(<Instance as InstanceWithFunction<
                 HList![i32, i32, i32, i32],
@@ -131,6 +198,11 @@ where
 
     /// Deallocates the `allocation` managed by the guest.
     pub fn deallocate(&mut self, allocation: GuestPointer) -> Result<(), RuntimeError> {
+        #[cfg(feature = "std")]
+        if let Some(handle) = &mut self.pooled_slot {
+            return handle.slot_mut().deallocate(allocation);
+        }
+
         if self.cabi_free.is_none() {
             self.cabi_free = Some(
                 <Instance as InstanceWithFunction<HList![i32], HList![]>>::load_function(
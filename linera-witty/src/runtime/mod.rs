@@ -0,0 +1,94 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Abstractions for driving a guest module through different underlying runtimes (Wasm backends,
+//! and the RISC-V backend added alongside them).
+
+mod memory;
+#[cfg(feature = "std")]
+mod pooling_allocator;
+#[cfg(feature = "riscv")]
+mod riscv;
+#[cfg(all(target_os = "linux", feature = "userfaultfd"))]
+mod userfaultfd_reset;
+
+pub use memory::{GuestPointer, Memory, RuntimeMemory};
+#[cfg(feature = "std")]
+pub use pooling_allocator::{InstanceAllocator, PoolingAllocator, PoolingAllocatorConfig};
+#[cfg(feature = "riscv")]
+pub use riscv::{RiscV, RiscVInstance, RiscVMemory};
+
+/// A backend capable of instantiating and calling into guest modules.
+pub trait Runtime: Sized {
+    /// The type used to access a guest instance's memory.
+    type Memory;
+}
+
+/// A running guest module instance, tied to the [`Runtime`] backend that created it.
+pub trait Instance {
+    /// The backend this instance was created by.
+    type Runtime: Runtime;
+}
+
+/// A guest module instance that exposes a function taking `Parameters` and returning `Results`.
+pub trait InstanceWithFunction<Parameters, Results>: Instance {
+    /// A loaded handle to the function, so that it doesn't need to be looked up by name on every
+    /// call.
+    type Function;
+
+    /// Loads the exported function named `name`.
+    fn load_function(&mut self, name: &str) -> Result<Self::Function, RuntimeError>;
+
+    /// Calls `function` with `parameters`, returning its results.
+    fn call(
+        &mut self,
+        function: &Self::Function,
+        parameters: Parameters,
+    ) -> Result<Results, RuntimeError>;
+}
+
+/// Errors that can occur while interfacing with a guest runtime.
+#[derive(Debug, thiserror::Error)]
+pub enum RuntimeError {
+    /// Attempted to allocate more memory than the guest's address space allows.
+    #[error("Requested allocation is too large for the guest's address space")]
+    AllocationTooLarge,
+
+    /// The guest module's allocator failed to service an allocation request.
+    #[error("Guest module failed to allocate the requested memory")]
+    AllocationFailed,
+
+    /// Attempted to deallocate an address that's not a valid allocation.
+    #[error("Attempted to deallocate an invalid address")]
+    DeallocateInvalidAddress,
+
+    /// A guest memory access fell outside of the instance's address space.
+    #[error("Guest memory access is out of bounds")]
+    OutOfBoundsAccess,
+
+    /// A [`PoolingAllocatorConfig`] had an invalid limit.
+    #[error("Invalid pooling allocator configuration: {0}")]
+    InvalidPoolingAllocatorConfig(&'static str),
+
+    /// [`PoolingAllocator::allocate`](InstanceAllocator::allocate) found no free instance slots.
+    #[error("Instance pool is exhausted; all slots are in use")]
+    InstancePoolExhausted,
+
+    /// Reserving the linear-memory region for a pooled instance slot failed.
+    #[error("Failed to reserve memory for a pooled instance slot")]
+    MemoryReservationFailed,
+
+    /// Resetting a pooled instance's linear memory failed.
+    #[error("Failed to reset pooled instance memory")]
+    MemoryResetFailed,
+
+    /// The RISC-V execution core fetched a word it doesn't know how to decode.
+    #[cfg(feature = "riscv")]
+    #[error("RISC-V instruction {0:#010x} is not supported")]
+    InvalidInstruction(u32),
+
+    /// The RISC-V execution core ran for longer than its step budget without halting.
+    #[cfg(feature = "riscv")]
+    #[error("RISC-V guest did not halt within its execution step budget")]
+    ExecutionTookTooLong,
+}
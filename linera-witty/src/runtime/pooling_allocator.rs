@@ -0,0 +1,515 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A pooling allocator for guest module instances.
+//!
+//! Instantiating a guest module normally asks the underlying runtime for a fresh linear memory
+//! (and table) on every call, which is the dominant cost when executing many short-lived guest
+//! invocations back to back. The [`PoolingAllocator`] instead reserves a fixed number of
+//! instance slots up front, each backed by a pre-allocated linear-memory region, and hands them
+//! out from a free-list. This trades a one-time, configurable amount of reserved memory for
+//! avoiding the instantiation cost on the hot path.
+//!
+//! This relies on `mmap`/`mprotect` and a `std::sync::Mutex`-guarded free-list, neither of which
+//! has an `alloc`-only equivalent, so the whole module is only available with the `std` feature
+//! enabled.
+
+#![cfg(feature = "std")]
+
+use super::{GuestPointer, RuntimeError};
+use std::sync::{Arc, Mutex};
+
+/// A source of guest module instance slots.
+///
+/// Implementations decide how (and when) the resources backing an instance are obtained. The
+/// default behavior of a runtime is to allocate everything on demand; [`PoolingAllocator`] is an
+/// alternative that pre-reserves a fixed pool of slots instead.
+pub trait InstanceAllocator {
+    /// The resources handed out for a single instance.
+    type Slot;
+
+    /// Obtains a [`Self::Slot`] to back a new guest module instance.
+    fn allocate(&self) -> Result<Self::Slot, RuntimeError>;
+
+    /// Returns a [`Self::Slot`] that's no longer in use so that it can be reused.
+    fn deallocate(&self, slot: Self::Slot);
+}
+
+/// Configuration limits for a [`PoolingAllocator`].
+///
+/// These are validated eagerly in [`PoolingAllocatorConfig::validate`] so that misconfiguration
+/// is reported at startup instead of surfacing as an allocation failure deep into execution.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PoolingAllocatorConfig {
+    /// The maximum number of instance slots kept in the pool.
+    pub max_instances: usize,
+    /// The maximum number of 64 KiB Wasm pages reserved for each instance's linear memory.
+    pub max_memory_pages: u32,
+    /// The maximum number of tables for each instance. The pool itself reserves no table storage;
+    /// only linear memory is pre-reserved per slot.
+    pub max_tables: u32,
+}
+
+impl PoolingAllocatorConfig {
+    /// Checks that the configured limits are usable, returning an error describing the first
+    /// problem found.
+    pub fn validate(&self) -> Result<(), RuntimeError> {
+        if self.max_instances == 0 {
+            return Err(RuntimeError::InvalidPoolingAllocatorConfig(
+                "`max_instances` must be at least 1",
+            ));
+        }
+        if self.max_memory_pages == 0 {
+            return Err(RuntimeError::InvalidPoolingAllocatorConfig(
+                "`max_memory_pages` must be at least 1",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// A pre-reserved linear-memory region backing one pooled instance slot.
+///
+/// The region is sized for [`PoolingAllocatorConfig::max_memory_pages`] up front, with guard
+/// pages surrounding it so that out-of-bounds guest accesses fault instead of corrupting
+/// unrelated memory.
+struct PooledMemory {
+    /// The mmap'd region, including the leading and trailing guard pages.
+    region: MmapRegion,
+    /// Offset of this slot's memory within the usable part of [`Self::region`], counted from the
+    /// end of the leading guard page (which [`MmapRegion`]'s methods skip on their own).
+    usable_offset: usize,
+    /// Size in bytes of the usable memory, i.e. `max_memory_pages * 64 KiB`.
+    usable_len: usize,
+    /// Number of Wasm pages committed to the guest. Set to zero when the slot is reserved;
+    /// nothing in this module updates it afterwards.
+    pages_in_use: u32,
+    /// A snapshot of the module's data segments, captured the first time this slot is loaded
+    /// with a module. [`PooledMemory::reset`] restores exactly this prefix of memory.
+    initial_image: Option<Arc<[u8]>>,
+    /// The highest offset written to since the last reset, i.e. the only part of memory that
+    /// can possibly differ from [`Self::initial_image`] (zero-padded).
+    dirty_high_water: usize,
+    /// The `userfaultfd` handler lazily reset through, if this build supports it. Created the
+    /// first time a reset needs it.
+    #[cfg(all(target_os = "linux", feature = "userfaultfd"))]
+    uffd_handler: Option<crate::runtime::userfaultfd_reset::UserFaultFdHandler>,
+}
+
+/// An instance slot handed out by a [`PoolingAllocator`].
+pub struct PooledSlot {
+    memory: PooledMemory,
+}
+
+impl PooledSlot {
+    /// Returns the usable portion of this slot's linear memory.
+    pub fn memory_mut(&mut self) -> &mut [u8] {
+        self.memory.region.usable_mut(self.memory.usable_offset)
+    }
+
+    /// Records the module's initial data image, so that future calls to [`Self::reset`] know
+    /// what to restore. Must be called once, right after the guest module's data segments have
+    /// been copied in for the first time.
+    pub fn set_initial_image(&mut self, initial_image: Arc<[u8]>) {
+        self.memory.initial_image = Some(initial_image);
+    }
+
+    /// Records that the guest has written up to (but not including) `offset`, so that
+    /// [`Self::reset`] knows how much of memory needs restoring.
+    pub fn mark_dirty_up_to(&mut self, offset: usize) {
+        self.memory.dirty_high_water = self.memory.dirty_high_water.max(offset);
+    }
+
+    /// Restores this slot's linear memory to its initial image, without re-mmap'ing it.
+    ///
+    /// Only the region between the start of memory and the high-water dirty offset is touched;
+    /// pages beyond it were never written to since the last reset and are already zero.
+    pub fn reset(&mut self) -> Result<(), RuntimeError> {
+        self.memory.reset()
+    }
+
+    /// Allocates `size` bytes from this slot's own pre-reserved memory using a simple bump
+    /// allocator, instead of calling into the guest module's `cabi_realloc`.
+    ///
+    /// This is what lets [`Memory`](super::memory::Memory) operate over a pooled slot
+    /// transparently: from the caller's point of view it's just another allocation.
+    pub fn allocate(&mut self, size: u32) -> Result<GuestPointer, RuntimeError> {
+        let start = self.memory.dirty_high_water;
+        let end = start
+            .checked_add(size as usize)
+            .filter(|&end| end <= self.memory.usable_len)
+            .ok_or(RuntimeError::AllocationFailed)?;
+
+        self.mark_dirty_up_to(end);
+        Ok(GuestPointer::new(start as u32))
+    }
+
+    /// Releases an allocation made by [`Self::allocate`].
+    ///
+    /// Individual allocations aren't tracked; the whole slot is reclaimed at once the next time
+    /// [`Self::reset`] runs, so this is a deliberate no-op.
+    pub fn deallocate(&mut self, _allocation: GuestPointer) -> Result<(), RuntimeError> {
+        Ok(())
+    }
+}
+
+impl PooledMemory {
+    fn reset(&mut self) -> Result<(), RuntimeError> {
+        if self.dirty_high_water == 0 {
+            return Ok(());
+        }
+
+        #[cfg(all(target_os = "linux", feature = "userfaultfd"))]
+        if self.reset_via_userfaultfd()? {
+            return Ok(());
+        }
+
+        self.region
+            .discard_dirty_pages(self.usable_offset, self.dirty_high_water)?;
+
+        if let Some(initial_image) = &self.initial_image {
+            let destination = self.region.usable_mut(self.usable_offset);
+            let image_len = initial_image.len().min(destination.len());
+            destination[..image_len].copy_from_slice(&initial_image[..image_len]);
+        }
+
+        self.dirty_high_water = 0;
+        Ok(())
+    }
+
+    /// Lazily re-arms `userfaultfd` over the dirty range instead of eagerly re-copying the
+    /// initial image, so that untouched pages cost nothing until they're actually faulted in.
+    ///
+    /// Returns `false` (falling back to the eager path) when there's no initial image to serve
+    /// faults from yet.
+    #[cfg(all(target_os = "linux", feature = "userfaultfd"))]
+    fn reset_via_userfaultfd(&mut self) -> Result<bool, RuntimeError> {
+        use crate::runtime::userfaultfd_reset::UserFaultFdHandler;
+
+        let Some(initial_image) = self.initial_image.clone() else {
+            return Ok(false);
+        };
+
+        if self.uffd_handler.is_none() {
+            self.uffd_handler = Some(UserFaultFdHandler::register(
+                self.region.usable_addr(self.usable_offset),
+                self.usable_len,
+                initial_image,
+            )?);
+        }
+
+        // `MADV_DONTNEED` drops the dirty pages immediately; the registered handler then serves
+        // each one lazily, the next time the guest touches it.
+        self.region
+            .discard_dirty_pages(self.usable_offset, self.dirty_high_water)?;
+        self.dirty_high_water = 0;
+        Ok(true)
+    }
+}
+
+/// An [`InstanceAllocator`] that reserves a fixed pool of instance slots up front.
+///
+/// Slots are handed out from a free-list. When the pool is exhausted,
+/// [`InstanceAllocator::allocate`] returns [`RuntimeError::InstancePoolExhausted`] so that
+/// callers can apply backpressure instead of falling back to an unbounded on-demand allocation.
+pub struct PoolingAllocator {
+    config: PoolingAllocatorConfig,
+    free_slots: Mutex<Vec<PooledSlot>>,
+}
+
+impl PoolingAllocator {
+    /// Creates a new pool, eagerly reserving `config.max_instances` slots.
+    pub fn new(config: PoolingAllocatorConfig) -> Result<Self, RuntimeError> {
+        config.validate()?;
+
+        let mut free_slots = Vec::with_capacity(config.max_instances);
+        for _ in 0..config.max_instances {
+            free_slots.push(PooledSlot {
+                memory: PooledMemory::reserve(config.max_memory_pages)?,
+            });
+        }
+
+        Ok(PoolingAllocator {
+            config,
+            free_slots: Mutex::new(free_slots),
+        })
+    }
+
+    /// Returns the configured limits for this pool.
+    pub fn config(&self) -> &PoolingAllocatorConfig {
+        &self.config
+    }
+
+    /// Allocates a slot and wraps it in a [`PooledSlotHandle`] that returns it to this pool
+    /// automatically when dropped.
+    pub fn allocate_handle(&self) -> Result<PooledSlotHandle<'_>, RuntimeError> {
+        Ok(PooledSlotHandle {
+            pool: self,
+            slot: Some(InstanceAllocator::allocate(self)?),
+        })
+    }
+}
+
+/// An owned [`PooledSlot`] borrowed from a [`PoolingAllocator`].
+///
+/// The slot is returned to the pool's free-list when this handle is dropped, so callers (such as
+/// [`Memory`](super::memory::Memory)) don't need to remember to release it themselves.
+pub struct PooledSlotHandle<'pool> {
+    pool: &'pool PoolingAllocator,
+    slot: Option<PooledSlot>,
+}
+
+impl PooledSlotHandle<'_> {
+    /// Returns the borrowed slot.
+    pub fn slot_mut(&mut self) -> &mut PooledSlot {
+        self.slot
+            .as_mut()
+            .expect("slot was already returned to the pool")
+    }
+}
+
+impl Drop for PooledSlotHandle<'_> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot.take() {
+            self.pool.deallocate(slot);
+        }
+    }
+}
+
+impl InstanceAllocator for PoolingAllocator {
+    type Slot = PooledSlot;
+
+    fn allocate(&self) -> Result<Self::Slot, RuntimeError> {
+        self.free_slots
+            .lock()
+            .expect("instance pool lock was poisoned")
+            .pop()
+            .ok_or(RuntimeError::InstancePoolExhausted)
+    }
+
+    fn deallocate(&self, mut slot: Self::Slot) {
+        // Reset before returning the slot to the free-list: otherwise the next tenant would see
+        // whatever guest memory the previous one left behind (a cross-invocation data leak), and
+        // `PooledSlot::allocate`'s bump pointer would never go back to zero, so the slot's usable
+        // capacity would shrink by a little more on every reuse until `allocate` permanently
+        // failed. If the reset itself fails, drop the slot instead of risking either of those by
+        // returning unreset memory to the pool; losing one slot is safer than serving it again.
+        if slot.reset().is_err() {
+            return;
+        }
+
+        self.free_slots
+            .lock()
+            .expect("instance pool lock was poisoned")
+            .push(slot);
+    }
+}
+
+/// A guard-paged mmap'd region reserved for a single pooled instance's linear memory.
+///
+/// The leading and trailing pages of the mapping are left with no access permissions, so that
+/// reads or writes just past the usable region fault immediately instead of silently touching
+/// another instance's memory.
+struct MmapRegion {
+    #[cfg(unix)]
+    base: *mut libc::c_void,
+    #[cfg(unix)]
+    len: usize,
+    #[cfg(not(unix))]
+    storage: Vec<u8>,
+}
+
+// Safety: the region is only ever accessed through `&mut self` methods on the owning
+// `PooledMemory`, so there is no concurrent access to the raw pointer.
+unsafe impl Send for MmapRegion {}
+
+impl PooledMemory {
+    fn reserve(max_pages: u32) -> Result<Self, RuntimeError> {
+        const WASM_PAGE_SIZE: usize = 64 * 1024;
+        const GUARD_PAGE_SIZE: usize = 4096;
+
+        let usable_size = max_pages as usize * WASM_PAGE_SIZE;
+
+        #[cfg(unix)]
+        let region = {
+            let total_len = GUARD_PAGE_SIZE + usable_size + GUARD_PAGE_SIZE;
+            // Safety: we immediately check the return value and only ever hand out the usable
+            // sub-slice, keeping the guard pages inaccessible.
+            let base = unsafe {
+                libc::mmap(
+                    std::ptr::null_mut(),
+                    total_len,
+                    libc::PROT_NONE,
+                    libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                    -1,
+                    0,
+                )
+            };
+            if base == libc::MAP_FAILED {
+                return Err(RuntimeError::MemoryReservationFailed);
+            }
+            // Safety: `base` was just checked to be a valid mapping of at least `total_len`
+            // bytes, and the usable sub-region doesn't overlap with the guard pages.
+            let usable_ptr = unsafe { base.add(GUARD_PAGE_SIZE) };
+            let result = unsafe {
+                libc::mprotect(
+                    usable_ptr,
+                    usable_size,
+                    libc::PROT_READ | libc::PROT_WRITE,
+                )
+            };
+            if result != 0 {
+                unsafe {
+                    libc::munmap(base, total_len);
+                }
+                return Err(RuntimeError::MemoryReservationFailed);
+            }
+            MmapRegion {
+                base,
+                len: total_len,
+            }
+        };
+
+        #[cfg(not(unix))]
+        let region = MmapRegion {
+            storage: vec![0; usable_size],
+        };
+
+        Ok(PooledMemory {
+            region,
+            usable_offset: 0,
+            usable_len: usable_size,
+            pages_in_use: 0,
+            initial_image: None,
+            dirty_high_water: 0,
+            #[cfg(all(target_os = "linux", feature = "userfaultfd"))]
+            uffd_handler: None,
+        })
+    }
+}
+
+impl MmapRegion {
+    #[cfg(unix)]
+    fn usable_mut(&mut self, offset: usize) -> &mut [u8] {
+        const GUARD_PAGE_SIZE: usize = 4096;
+        let usable_len = self.len - 2 * GUARD_PAGE_SIZE;
+        // Safety: `base + GUARD_PAGE_SIZE .. base + GUARD_PAGE_SIZE + usable_len` is the mapped,
+        // readable/writable sub-region reserved in `PooledMemory::reserve`.
+        unsafe {
+            std::slice::from_raw_parts_mut(
+                (self.base as *mut u8).add(GUARD_PAGE_SIZE + offset),
+                usable_len - offset,
+            )
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn usable_mut(&mut self, offset: usize) -> &mut [u8] {
+        &mut self.storage[offset..]
+    }
+
+    /// Returns the address of byte `offset` within the usable region, for handing to APIs (like
+    /// `userfaultfd`) that need a raw address rather than a slice.
+    #[cfg(all(target_os = "linux", feature = "userfaultfd"))]
+    fn usable_addr(&self, offset: usize) -> usize {
+        const GUARD_PAGE_SIZE: usize = 4096;
+        self.base as usize + GUARD_PAGE_SIZE + offset
+    }
+
+    /// Cheaply discards everything written between `usable_offset` and `usable_offset +
+    /// dirty_len`, so that the pages fault back in as zero on next access.
+    ///
+    /// On Linux this is a `madvise(MADV_DONTNEED)` over the dirty range: the kernel drops the
+    /// physical pages immediately without zeroing anything itself, deferring that work to the
+    /// page fault the next write causes. Everywhere else we fall back to an explicit memset,
+    /// which is portable but pays the zeroing cost up front instead of lazily.
+    #[cfg(target_os = "linux")]
+    fn discard_dirty_pages(&mut self, usable_offset: usize, dirty_len: usize) -> Result<(), RuntimeError> {
+        const GUARD_PAGE_SIZE: usize = 4096;
+        const PAGE_SIZE: usize = 4096;
+
+        // Safety: `base + GUARD_PAGE_SIZE + usable_offset` through `dirty_len` bytes lies within
+        // the mapped, writable sub-region reserved in `PooledMemory::reserve`.
+        let dirty_start = unsafe { (self.base as *mut u8).add(GUARD_PAGE_SIZE + usable_offset) };
+        // `MADV_DONTNEED` only needs to cover whole pages; rounding up is harmless since any
+        // extra bytes are re-filled from `initial_image` (or are already zero) afterwards.
+        let aligned_len = dirty_len.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+
+        // Safety: `dirty_start .. dirty_start + aligned_len` is contained within this mapping,
+        // as `reserve` sized the usable region to `max_memory_pages` Wasm pages.
+        let result = unsafe {
+            libc::madvise(
+                dirty_start as *mut libc::c_void,
+                aligned_len,
+                libc::MADV_DONTNEED,
+            )
+        };
+        if result != 0 {
+            return Err(RuntimeError::MemoryResetFailed);
+        }
+        Ok(())
+    }
+
+    /// Portable fallback for platforms without `madvise`: explicitly zero the dirty range.
+    #[cfg(not(target_os = "linux"))]
+    fn discard_dirty_pages(&mut self, usable_offset: usize, dirty_len: usize) -> Result<(), RuntimeError> {
+        let memory = self.usable_mut(usable_offset);
+        let len = dirty_len.min(memory.len());
+        memory[..len].fill(0);
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+impl Drop for MmapRegion {
+    fn drop(&mut self) {
+        // Safety: `self.base` was obtained from a matching `mmap` call of `self.len` bytes in
+        // `PooledMemory::reserve`, and is never used after this point.
+        unsafe {
+            libc::munmap(self.base, self.len);
+        }
+    }
+}
+
+#[test]
+fn test_pooling_allocator_allocate_deallocate_and_exhaustion() {
+    let config = PoolingAllocatorConfig {
+        max_instances: 2,
+        max_memory_pages: 1,
+        max_tables: 1,
+    };
+    let pool = PoolingAllocator::new(config).unwrap();
+
+    let first = pool.allocate().unwrap();
+    let _second = pool.allocate().unwrap();
+    assert!(matches!(
+        pool.allocate(),
+        Err(RuntimeError::InstancePoolExhausted)
+    ));
+
+    pool.deallocate(first);
+    assert!(pool.allocate().is_ok());
+}
+
+#[test]
+fn test_pooled_memory_reset_round_trip() {
+    let config = PoolingAllocatorConfig {
+        max_instances: 1,
+        max_memory_pages: 1,
+        max_tables: 1,
+    };
+    let pool = PoolingAllocator::new(config).unwrap();
+    let mut slot = pool.allocate().unwrap();
+
+    slot.set_initial_image(Arc::from(vec![1u8, 2, 3, 4]));
+    let memory = slot.memory_mut();
+    memory[..4].copy_from_slice(&[9, 9, 9, 9]);
+    memory[100] = 42;
+    slot.mark_dirty_up_to(101);
+
+    slot.reset().unwrap();
+
+    let memory = slot.memory_mut();
+    assert_eq!(&memory[..4], &[1, 2, 3, 4]);
+    assert_eq!(memory[100], 0);
+}
@@ -0,0 +1,515 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! A [`Runtime`] implementation targeting a register-machine RISC-V guest, as an alternative to
+//! the Wasm backends.
+//!
+//! Compiling user-defined applications to a small, deterministic RISC-V subset (RV32E/RV64-style,
+//! in the spirit of PolkaVM) instead of Wasm gives a lighter interpreter/JIT that's easier to
+//! gas-meter deterministically. [`RiscVInstance::run`] is that interpreter: a fetch/decode/execute
+//! loop over the RV32I integer arithmetic, load/store, branch and jump instructions plus `ecall`
+//! (no `fence`, `ebreak` or CSR instructions), operating on the instance's registers and memory.
+//! This module also provides [`RuntimeMemory`] over the VM's flat guest address
+//! space and the `cabi_realloc`/`cabi_free` allocation convention; everything above that (the WIT
+//! marshalling in [`crate::type_traits`]) is reused unchanged.
+
+#![cfg(feature = "riscv")]
+
+use super::{GuestPointer, Instance, InstanceWithFunction, Runtime, RuntimeError, RuntimeMemory};
+use frunk::{hlist, hlist_pat, HList};
+use std::borrow::Cow;
+
+/// Marker type identifying the RISC-V backend to the generic [`Runtime`] machinery.
+pub struct RiscV;
+
+impl Runtime for RiscV {
+    type Memory = RiscVMemory;
+}
+
+/// The number of instructions [`RiscVInstance::run`] will execute before giving up and returning
+/// [`RuntimeError::ExecutionTookTooLong`], as a backstop against a guest program that never
+/// reaches an `ecall`.
+const MAX_STEPS: u32 = 1_000_000;
+
+/// A running RISC-V guest: its register file, program counter, and flat linear address space.
+///
+/// Unlike the Wasm backends, there's a single contiguous address space per instance, so
+/// [`RiscVMemory`] doesn't need a handle into the host runtime to resolve a [`GuestPointer`] —
+/// it indexes straight into [`Self::memory`].
+pub struct RiscVInstance {
+    registers: [u64; 32],
+    pc: u32,
+    memory: Vec<u8>,
+    /// Address of the next word available to the bump allocator, which serves every
+    /// `cabi_realloc`/`cabi_free` call; the guest's own exports are not looked up.
+    bump_pointer: u32,
+}
+
+impl RiscVInstance {
+    /// Creates a new instance with `memory_size` bytes of flat address space, zero-initialized.
+    pub fn new(memory_size: u32) -> Self {
+        RiscVInstance {
+            registers: [0; 32],
+            pc: 0,
+            memory: vec![0; memory_size as usize],
+            bump_pointer: 0,
+        }
+    }
+
+    /// Returns the current value of register `index` (`x0`..=`x31`). `x0` always reads as zero.
+    pub fn register(&self, index: u32) -> u64 {
+        self.registers[index as usize]
+    }
+
+    /// Sets register `index` to `value`. Writes to `x0` are silently discarded, matching the
+    /// RISC-V convention that it's hardwired to zero.
+    fn set_register(&mut self, index: u32, value: u64) {
+        if index != 0 {
+            self.registers[index as usize] = value;
+        }
+    }
+
+    /// Runs the guest starting at `entry_pc` until it executes an `ecall` (the convention this
+    /// backend uses for a guest function returning control to the host), fetching and executing
+    /// one instruction at a time.
+    ///
+    /// Bounded by [`MAX_STEPS`] so that a guest program that never halts can't hang the host
+    /// forever; exceeding it is reported as [`RuntimeError::ExecutionTookTooLong`] rather than
+    /// looping indefinitely.
+    pub fn run(&mut self, entry_pc: u32) -> Result<(), RuntimeError> {
+        self.pc = entry_pc;
+
+        for _ in 0..MAX_STEPS {
+            let instruction = self.fetch(self.pc)?;
+            if self.execute(instruction)? {
+                return Ok(());
+            }
+        }
+
+        Err(RuntimeError::ExecutionTookTooLong)
+    }
+
+    /// Reads the little-endian 32-bit instruction word at `pc`.
+    fn fetch(&self, pc: u32) -> Result<u32, RuntimeError> {
+        let start = pc as usize;
+        let end = start
+            .checked_add(4)
+            .filter(|&end| end <= self.memory.len())
+            .ok_or(RuntimeError::OutOfBoundsAccess)?;
+
+        Ok(u32::from_le_bytes(self.memory[start..end].try_into().unwrap()))
+    }
+
+    /// Decodes and executes one instruction, advancing [`Self::pc`] unless the instruction itself
+    /// redirects it (a branch or jump). Returns `true` if this was an `ecall`, signaling the
+    /// caller to stop.
+    fn execute(&mut self, instruction: u32) -> Result<bool, RuntimeError> {
+        let opcode = instruction & 0x7f;
+        let rd = (instruction >> 7) & 0x1f;
+        let funct3 = (instruction >> 12) & 0x7;
+        let rs1 = (instruction >> 15) & 0x1f;
+        let rs2 = (instruction >> 20) & 0x1f;
+        let funct7 = (instruction >> 25) & 0x7f;
+
+        let i_imm = (instruction as i32) >> 20;
+        let s_imm = (((instruction & 0xfe00_0000) as i32) >> 20) | ((instruction >> 7) & 0x1f) as i32;
+        let b_imm = decode_b_immediate(instruction);
+        let u_imm = (instruction & 0xffff_f000) as i32;
+        let j_imm = decode_j_immediate(instruction);
+
+        let mut next_pc = self.pc.wrapping_add(4);
+        let mut halt = false;
+
+        match opcode {
+            // OP-IMM: register-immediate arithmetic/logic.
+            0x13 => {
+                let rs1_value = self.register(rs1) as i32;
+                let result = match funct3 {
+                    0b000 => rs1_value.wrapping_add(i_imm),
+                    0b111 => rs1_value & i_imm,
+                    0b110 => rs1_value | i_imm,
+                    0b100 => rs1_value ^ i_imm,
+                    0b010 => i32::from(rs1_value < i_imm),
+                    0b011 => i32::from((rs1_value as u32) < (i_imm as u32)),
+                    0b001 => rs1_value.wrapping_shl(i_imm as u32 & 0x1f),
+                    0b101 if funct7 & 0x20 == 0 => {
+                        ((rs1_value as u32).wrapping_shr(i_imm as u32 & 0x1f)) as i32
+                    }
+                    0b101 => rs1_value.wrapping_shr(i_imm as u32 & 0x1f),
+                    _ => return Err(RuntimeError::InvalidInstruction(instruction)),
+                };
+                self.set_register(rd, result as u32 as u64);
+            }
+            // OP: register-register arithmetic/logic.
+            0x33 => {
+                let a = self.register(rs1) as i32;
+                let b = self.register(rs2) as i32;
+                let result = match (funct3, funct7) {
+                    (0b000, 0x00) => a.wrapping_add(b),
+                    (0b000, 0x20) => a.wrapping_sub(b),
+                    (0b111, _) => a & b,
+                    (0b110, _) => a | b,
+                    (0b100, _) => a ^ b,
+                    (0b010, _) => i32::from(a < b),
+                    (0b011, _) => i32::from((a as u32) < (b as u32)),
+                    (0b001, _) => a.wrapping_shl(b as u32 & 0x1f),
+                    (0b101, 0x00) => ((a as u32).wrapping_shr(b as u32 & 0x1f)) as i32,
+                    (0b101, 0x20) => a.wrapping_shr(b as u32 & 0x1f),
+                    _ => return Err(RuntimeError::InvalidInstruction(instruction)),
+                };
+                self.set_register(rd, result as u32 as u64);
+            }
+            // LOAD: read from memory into a register.
+            0x03 => {
+                let address = (self.register(rs1) as i32).wrapping_add(i_imm) as u32 as usize;
+                let value = match funct3 {
+                    0b000 => self.load_bytes(address, 1)?[0] as i8 as i64 as u64,
+                    0b001 => i16::from_le_bytes(self.load_bytes(address, 2)?.try_into().unwrap())
+                        as i64 as u64,
+                    0b010 => i32::from_le_bytes(self.load_bytes(address, 4)?.try_into().unwrap())
+                        as i64 as u64,
+                    0b100 => self.load_bytes(address, 1)?[0] as u64,
+                    0b101 => {
+                        u16::from_le_bytes(self.load_bytes(address, 2)?.try_into().unwrap()) as u64
+                    }
+                    _ => return Err(RuntimeError::InvalidInstruction(instruction)),
+                };
+                self.set_register(rd, value);
+            }
+            // STORE: write a register's low bytes to memory.
+            0x23 => {
+                let address = (self.register(rs1) as i32).wrapping_add(s_imm) as u32 as usize;
+                let value = self.register(rs2);
+                let bytes: &[u8] = match funct3 {
+                    0b000 => &value.to_le_bytes()[..1],
+                    0b001 => &value.to_le_bytes()[..2],
+                    0b010 => &value.to_le_bytes()[..4],
+                    _ => return Err(RuntimeError::InvalidInstruction(instruction)),
+                };
+                self.store_bytes(address, bytes)?;
+            }
+            // BRANCH: compare two registers and conditionally redirect the program counter.
+            0x63 => {
+                let a = self.register(rs1) as u32;
+                let b = self.register(rs2) as u32;
+                let taken = match funct3 {
+                    0b000 => a == b,
+                    0b001 => a != b,
+                    0b100 => (a as i32) < (b as i32),
+                    0b101 => (a as i32) >= (b as i32),
+                    0b110 => a < b,
+                    0b111 => a >= b,
+                    _ => return Err(RuntimeError::InvalidInstruction(instruction)),
+                };
+                if taken {
+                    next_pc = self.pc.wrapping_add(b_imm as u32);
+                }
+            }
+            // JAL: unconditional jump, recording the return address.
+            0x6f => {
+                self.set_register(rd, next_pc as u64);
+                next_pc = self.pc.wrapping_add(j_imm as u32);
+            }
+            // JALR: unconditional jump to a register plus an offset.
+            0x67 => {
+                let target = (self.register(rs1) as i32).wrapping_add(i_imm) as u32 & !1;
+                self.set_register(rd, next_pc as u64);
+                next_pc = target;
+            }
+            // LUI: load an upper immediate.
+            0x37 => self.set_register(rd, u_imm as u32 as u64),
+            // AUIPC: add an upper immediate to the program counter.
+            0x17 => self.set_register(rd, self.pc.wrapping_add(u_imm as u32) as u64),
+            // SYSTEM: only `ecall` (all other fields zero) is supported, as the "return to host"
+            // signal that ends a call into the guest.
+            0x73 if instruction == 0x73 => halt = true,
+            _ => return Err(RuntimeError::InvalidInstruction(instruction)),
+        }
+
+        self.pc = next_pc;
+        Ok(halt)
+    }
+
+    fn load_bytes(&self, address: usize, length: usize) -> Result<&[u8], RuntimeError> {
+        let end = address
+            .checked_add(length)
+            .filter(|&end| end <= self.memory.len())
+            .ok_or(RuntimeError::OutOfBoundsAccess)?;
+        Ok(&self.memory[address..end])
+    }
+
+    fn store_bytes(&mut self, address: usize, bytes: &[u8]) -> Result<(), RuntimeError> {
+        let end = address
+            .checked_add(bytes.len())
+            .filter(|&end| end <= self.memory.len())
+            .ok_or(RuntimeError::OutOfBoundsAccess)?;
+        self.memory[address..end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Allocates `size` bytes using the built-in bump allocator, which backs every
+    /// `cabi_realloc` call whether or not the guest exports its own.
+    ///
+    /// Bump-allocated memory is never reclaimed by [`Self::bump_deallocate`]; it only exists to
+    /// let simple guests that never free anything skip exporting a real allocator.
+    fn bump_allocate(&mut self, size: u32) -> Result<GuestPointer, RuntimeError> {
+        const ALIGNMENT: u32 = 8;
+        let aligned_start = (self.bump_pointer + ALIGNMENT - 1) & !(ALIGNMENT - 1);
+        let end = aligned_start
+            .checked_add(size)
+            .ok_or(RuntimeError::AllocationTooLarge)?;
+
+        if end as usize > self.memory.len() {
+            return Err(RuntimeError::AllocationFailed);
+        }
+
+        self.bump_pointer = end;
+        Ok(GuestPointer::new(aligned_start))
+    }
+
+    fn bump_deallocate(&mut self, _allocation: GuestPointer) -> Result<(), RuntimeError> {
+        // The bump allocator never reclaims memory; deallocation is a deliberate no-op so guests
+        // built without a real allocator can still call through the usual `Memory` API.
+        Ok(())
+    }
+}
+
+/// Decodes a B-type (branch) instruction's signed byte offset, whose bits are scattered across
+/// the word in the order the RISC-V spec uses to simplify hardware decode.
+fn decode_b_immediate(instruction: u32) -> i32 {
+    let imm_12 = (instruction >> 31) & 0x1;
+    let imm_10_5 = (instruction >> 25) & 0x3f;
+    let imm_4_1 = (instruction >> 8) & 0xf;
+    let imm_11 = (instruction >> 7) & 0x1;
+
+    let raw = (imm_12 << 12) | (imm_11 << 11) | (imm_10_5 << 5) | (imm_4_1 << 1);
+    // Sign-extend from bit 12.
+    ((raw << 19) as i32) >> 19
+}
+
+/// Decodes a J-type (jump) instruction's signed byte offset, whose bits are likewise scattered.
+fn decode_j_immediate(instruction: u32) -> i32 {
+    let imm_20 = (instruction >> 31) & 0x1;
+    let imm_19_12 = (instruction >> 12) & 0xff;
+    let imm_11 = (instruction >> 20) & 0x1;
+    let imm_10_1 = (instruction >> 21) & 0x3ff;
+
+    let raw = (imm_20 << 20) | (imm_19_12 << 12) | (imm_11 << 11) | (imm_10_1 << 1);
+    // Sign-extend from bit 20.
+    ((raw << 11) as i32) >> 11
+}
+
+impl Instance for RiscVInstance {
+    type Runtime = RiscV;
+}
+
+/// Maps the WIT Canonical ABI's `cabi_realloc(old_ptr, old_size, align, new_size) -> ptr`
+/// convention onto [`RiscVInstance::bump_allocate`], so that guests can be driven through the
+/// shared [`Memory`](super::memory::Memory) API. A `cabi_realloc` exported by the guest itself is
+/// not called; the bump allocator always serves the request.
+impl InstanceWithFunction<HList![i32, i32, i32, i32], HList![i32]> for RiscVInstance {
+    /// Guests are never required to export `cabi_realloc` themselves; there's nothing to load.
+    type Function = ();
+
+    fn load_function(&mut self, _name: &str) -> Result<Self::Function, RuntimeError> {
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        (): &Self::Function,
+        parameters: HList![i32, i32, i32, i32],
+    ) -> Result<HList![i32], RuntimeError> {
+        let hlist_pat![_old_address, _old_size, _alignment, new_size] = parameters;
+        let size = u32::try_from(new_size).map_err(|_| RuntimeError::AllocationTooLarge)?;
+        let address = self.bump_allocate(size)?;
+
+        Ok(hlist![address.as_u32() as i32])
+    }
+}
+
+/// Maps the WIT Canonical ABI's `cabi_free(ptr)` convention onto
+/// [`RiscVInstance::bump_deallocate`].
+impl InstanceWithFunction<HList![i32], HList![]> for RiscVInstance {
+    type Function = ();
+
+    fn load_function(&mut self, _name: &str) -> Result<Self::Function, RuntimeError> {
+        Ok(())
+    }
+
+    fn call(
+        &mut self,
+        (): &Self::Function,
+        parameters: HList![i32],
+    ) -> Result<HList![], RuntimeError> {
+        let hlist_pat![address] = parameters;
+        self.bump_deallocate(GuestPointer::new(address as u32))?;
+
+        Ok(hlist![])
+    }
+}
+
+/// [`RuntimeMemory`] implementation over a [`RiscVInstance`]'s flat address space.
+///
+/// Every access is bounds-checked against the instance's actual memory size, since unlike native
+/// Wasm linear memory there's no guard-page trap to rely on here.
+pub struct RiscVMemory;
+
+impl RuntimeMemory<RiscVInstance> for RiscVMemory {
+    fn read<'instance>(
+        &self,
+        instance: &'instance RiscVInstance,
+        location: GuestPointer,
+        length: u32,
+    ) -> Result<Cow<'instance, [u8]>, RuntimeError> {
+        let start = location.as_u32() as usize;
+        let end = start
+            .checked_add(length as usize)
+            .filter(|&end| end <= instance.memory.len())
+            .ok_or(RuntimeError::OutOfBoundsAccess)?;
+
+        Ok(Cow::Borrowed(&instance.memory[start..end]))
+    }
+
+    fn write(
+        &mut self,
+        instance: &mut RiscVInstance,
+        location: GuestPointer,
+        bytes: &[u8],
+    ) -> Result<(), RuntimeError> {
+        let start = location.as_u32() as usize;
+        let end = start
+            .checked_add(bytes.len())
+            .filter(|&end| end <= instance.memory.len())
+            .ok_or(RuntimeError::OutOfBoundsAccess)?;
+
+        instance.memory[start..end].copy_from_slice(bytes);
+        Ok(())
+    }
+}
+
+#[test]
+fn test_riscv_memory_read_write_bounds_checking() {
+    let mut instance = RiscVInstance::new(16);
+    let mut memory = RiscVMemory;
+
+    memory
+        .write(&mut instance, GuestPointer::new(0), &[1, 2, 3, 4])
+        .unwrap();
+    let read = memory.read(&instance, GuestPointer::new(0), 4).unwrap();
+    assert_eq!(&*read, &[1, 2, 3, 4]);
+
+    assert!(memory
+        .write(&mut instance, GuestPointer::new(14), &[0; 4])
+        .is_err());
+}
+
+#[test]
+fn test_riscv_bump_allocator() {
+    let mut instance = RiscVInstance::new(64);
+
+    let first = instance.bump_allocate(8).unwrap();
+    let second = instance.bump_allocate(8).unwrap();
+    assert_ne!(first, second);
+
+    assert!(instance.bump_allocate(1000).is_err());
+}
+
+/// Encodes an I-type instruction (e.g. `addi rd, rs1, imm`).
+fn encode_i_type(opcode: u32, funct3: u32, rd: u32, rs1: u32, imm: i32) -> u32 {
+    ((imm as u32) << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+/// Encodes an R-type instruction (e.g. `add rd, rs1, rs2`).
+fn encode_r_type(opcode: u32, funct3: u32, funct7: u32, rd: u32, rs1: u32, rs2: u32) -> u32 {
+    (funct7 << 25) | (rs2 << 20) | (rs1 << 15) | (funct3 << 12) | (rd << 7) | opcode
+}
+
+/// Encodes a J-type (jump) instruction (e.g. `jal rd, imm`).
+fn encode_j_type(rd: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    let imm_20 = (imm >> 20) & 0x1;
+    let imm_10_1 = (imm >> 1) & 0x3ff;
+    let imm_11 = (imm >> 11) & 0x1;
+    let imm_19_12 = (imm >> 12) & 0xff;
+
+    (imm_20 << 31) | (imm_10_1 << 21) | (imm_11 << 20) | (imm_19_12 << 12) | (rd << 7) | 0x6f
+}
+
+/// Encodes a B-type (branch) instruction (e.g. `beq rs1, rs2, imm`).
+fn encode_b_type(funct3: u32, rs1: u32, rs2: u32, imm: i32) -> u32 {
+    let imm = imm as u32;
+    let imm_12 = (imm >> 12) & 0x1;
+    let imm_11 = (imm >> 11) & 0x1;
+    let imm_10_5 = (imm >> 5) & 0x3f;
+    let imm_4_1 = (imm >> 1) & 0xf;
+
+    (imm_12 << 31)
+        | (imm_10_5 << 25)
+        | (rs2 << 20)
+        | (rs1 << 15)
+        | (funct3 << 12)
+        | (imm_4_1 << 8)
+        | (imm_11 << 7)
+        | 0x63
+}
+
+#[test]
+fn test_riscv_run_executes_addi_and_add_then_halts_on_ecall() {
+    let mut instance = RiscVInstance::new(4096);
+    let program = [
+        encode_i_type(0x13, 0b000, 1, 0, 5), // addi x1, x0, 5
+        encode_i_type(0x13, 0b000, 2, 0, 7), // addi x2, x0, 7
+        encode_r_type(0x33, 0b000, 0x00, 3, 1, 2), // add x3, x1, x2
+        0x73,                                 // ecall
+    ];
+
+    write_program(&mut instance, &program);
+
+    instance.run(0).unwrap();
+
+    assert_eq!(instance.register(1), 5);
+    assert_eq!(instance.register(2), 7);
+    assert_eq!(instance.register(3), 12);
+}
+
+#[test]
+fn test_riscv_run_takes_the_branch_when_condition_holds() {
+    let mut instance = RiscVInstance::new(4096);
+    let program = [
+        encode_i_type(0x13, 0b000, 1, 0, 1), // addi x1, x0, 1
+        encode_i_type(0x13, 0b000, 2, 0, 1), // addi x2, x0, 1
+        encode_b_type(0b000, 1, 2, 8), // beq x1, x2, +8 (skip the next instruction)
+        encode_i_type(0x13, 0b000, 3, 0, 99), // addi x3, x0, 99 (skipped)
+        0x73,                                 // ecall
+    ];
+    write_program(&mut instance, &program);
+
+    instance.run(0).unwrap();
+
+    assert_eq!(instance.register(3), 0);
+}
+
+#[test]
+fn test_riscv_run_reports_when_the_guest_never_halts() {
+    let mut instance = RiscVInstance::new(4096);
+    // `jal x0, 0`: an unconditional jump to itself, looping forever and never reaching an
+    // `ecall`.
+    let infinite_loop = encode_j_type(0, 0);
+    write_program(&mut instance, &[infinite_loop]);
+
+    assert!(matches!(
+        instance.run(0),
+        Err(RuntimeError::ExecutionTookTooLong)
+    ));
+}
+
+/// Writes `program` as consecutive little-endian 32-bit words starting at address 0, looping the
+/// last instruction to fill any unused space isn't necessary: `run` only fetches as many words as
+/// it executes, so trailing zeroed memory is never reached as long as the program halts itself.
+fn write_program(instance: &mut RiscVInstance, program: &[u32]) {
+    for (index, instruction) in program.iter().enumerate() {
+        let address = index * 4;
+        instance.memory[address..address + 4].copy_from_slice(&instruction.to_le_bytes());
+    }
+}
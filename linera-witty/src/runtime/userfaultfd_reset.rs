@@ -0,0 +1,150 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! An optional, `userfaultfd`-backed alternative to `madvise(MADV_DONTNEED)` for resetting a
+//! pooled instance's linear memory.
+//!
+//! Instead of eagerly re-copying the module's initial data image on every
+//! [`PooledSlot::reset`](super::pooling_allocator::PooledSlot::reset), this handler registers the
+//! usable region of a slot's memory with the kernel's userfault mechanism and lazily serves each
+//! page the first time it's touched after a reset: either zero-filled, or copied from the
+//! initial image if the page falls within it. Pages the guest never touches again cost nothing.
+//!
+//! Serving faults requires something to actually read them off the `userfaultfd` file descriptor
+//! and resolve them; [`UserFaultFdHandler::register`] spawns a dedicated thread that does exactly
+//! that for as long as the handler is alive, since a guest's very next access to a discarded page
+//! would otherwise block forever waiting for a resolution nobody provides.
+//!
+//! This is strictly an optimization over the `madvise` path and is only available on Linux with
+//! the `userfaultfd` feature enabled; the portable reset path in [`super::pooling_allocator`]
+//! remains correct (if less lazy) everywhere else.
+
+#![cfg(all(target_os = "linux", feature = "userfaultfd"))]
+
+use super::RuntimeError;
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+    time::Duration,
+};
+use userfaultfd::{Event, Uffd, UffdBuilder};
+
+/// How long the handler thread sleeps between polls of the `userfaultfd` when there's no event
+/// ready, so that it notices a shutdown request promptly without busy-spinning.
+const POLL_INTERVAL: Duration = Duration::from_micros(200);
+
+/// Serves page faults for one pooled instance's linear memory.
+///
+/// One handler is created per [`PoolingAllocator`](super::pooling_allocator::PoolingAllocator)
+/// slot and kept alive for as long as the slot is registered with the kernel. The dedicated
+/// thread spawned by [`Self::register`] is stopped and joined when this handler is dropped.
+pub struct UserFaultFdHandler {
+    stop: Arc<AtomicBool>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl UserFaultFdHandler {
+    /// Registers `region_start..region_start + region_len` (a slot's usable memory) for
+    /// userfault handling and spawns the thread that serves faults by zero-filling or copying
+    /// from `initial_image`.
+    pub fn register(
+        region_start: usize,
+        region_len: usize,
+        initial_image: Arc<[u8]>,
+    ) -> Result<Self, RuntimeError> {
+        let uffd = UffdBuilder::new()
+            .close_on_exec(true)
+            .non_blocking(true)
+            .user_mode_only(true)
+            .create()
+            .map_err(|_| RuntimeError::MemoryResetFailed)?;
+
+        // Safety: `region_start..region_start + region_len` is a page-aligned mapping owned by
+        // the caller for the lifetime of this handler.
+        unsafe {
+            uffd.register(region_start as *mut _, region_len)
+                .map_err(|_| RuntimeError::MemoryResetFailed)?;
+        }
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let worker_stop = Arc::clone(&stop);
+        let worker = std::thread::Builder::new()
+            .name("witty-uffd-handler".into())
+            .spawn(move || run_fault_handler_loop(uffd, initial_image, region_start, region_len, worker_stop))
+            .map_err(|_| RuntimeError::MemoryResetFailed)?;
+
+        Ok(UserFaultFdHandler {
+            stop,
+            worker: Some(worker),
+        })
+    }
+}
+
+impl Drop for UserFaultFdHandler {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Polls `uffd` for page-fault events and resolves them until `stop` is set or the file
+/// descriptor errors out (e.g. because the region was unregistered).
+fn run_fault_handler_loop(
+    uffd: Uffd,
+    initial_image: Arc<[u8]>,
+    region_start: usize,
+    region_len: usize,
+    stop: Arc<AtomicBool>,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        match uffd.read_event() {
+            Ok(Some(Event::Pagefault { addr, .. })) => {
+                // A fault we fail to resolve would otherwise leave the guest blocked forever on
+                // this page; there's no caller left to report the error to, so best effort is all
+                // that's left here.
+                let _ = resolve_fault(&uffd, &initial_image, region_start, region_len, addr as usize);
+            }
+            Ok(Some(_)) => {}
+            Ok(None) => std::thread::sleep(POLL_INTERVAL),
+            Err(_) => break,
+        }
+    }
+}
+
+/// Resolves a single fault at `fault_addr`, copying from the initial image where the faulting
+/// page overlaps it and zero-filling the rest.
+fn resolve_fault(
+    uffd: &Uffd,
+    initial_image: &Arc<[u8]>,
+    region_start: usize,
+    region_len: usize,
+    fault_addr: usize,
+) -> Result<(), RuntimeError> {
+    const PAGE_SIZE: usize = 4096;
+    let page_start = fault_addr & !(PAGE_SIZE - 1);
+    let offset_in_region = page_start
+        .checked_sub(region_start)
+        .filter(|&offset| offset < region_len)
+        .ok_or(RuntimeError::OutOfBoundsAccess)?;
+
+    let mut page = [0u8; PAGE_SIZE];
+    if offset_in_region < initial_image.len() {
+        let available = (initial_image.len() - offset_in_region).min(PAGE_SIZE);
+        page[..available]
+            .copy_from_slice(&initial_image[offset_in_region..offset_in_region + available]);
+    }
+
+    // Safety: `page_start` is the start of the faulting page within the region we registered,
+    // and `page` supplies exactly one page of data.
+    unsafe {
+        uffd.copy(page.as_ptr() as *const _, page_start as *mut _, PAGE_SIZE, true)
+            .map_err(|_| RuntimeError::MemoryResetFailed)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,6 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`](super::WitType) implementations for common Rust types.
+
+mod std;
@@ -0,0 +1,22 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`] implementations for the floating point primitive types.
+
+use crate::{Layout, WitType};
+
+macro_rules! impl_float {
+    ($type:ty, $size:expr) => {
+        impl WitType for $type {
+            const SIZE: u32 = $size;
+            type Layout = Self;
+        }
+
+        impl Layout for $type {
+            const ALIGNMENT: u32 = $size;
+        }
+    };
+}
+
+impl_float!(f32, 4);
+impl_float!(f64, 8);
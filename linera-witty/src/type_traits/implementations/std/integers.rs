@@ -0,0 +1,28 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`] implementations for the integer primitive types.
+
+use crate::{Layout, WitType};
+
+macro_rules! impl_integer {
+    ($type:ty, $size:expr) => {
+        impl WitType for $type {
+            const SIZE: u32 = $size;
+            type Layout = Self;
+        }
+
+        impl Layout for $type {
+            const ALIGNMENT: u32 = $size;
+        }
+    };
+}
+
+impl_integer!(i8, 1);
+impl_integer!(u8, 1);
+impl_integer!(i16, 2);
+impl_integer!(u16, 2);
+impl_integer!(i32, 4);
+impl_integer!(u32, 4);
+impl_integer!(i64, 8);
+impl_integer!(u64, 8);
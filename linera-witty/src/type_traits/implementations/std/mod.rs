@@ -2,6 +2,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 //! Implementations of the custom traits for types from the standard library.
+//!
+//! These only need `alloc`, not the full standard library, so they're available even when this
+//! crate is built with `default-features = false` (`no_std` plus `alloc`). The crate-level `std`
+//! feature only gates helpers that genuinely need an OS, none of which live here.
 
 mod floats;
 mod integers;
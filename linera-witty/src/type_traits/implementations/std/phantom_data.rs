@@ -0,0 +1,17 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`] implementation for [`PhantomData`], so that generic wrappers can carry a marker
+//! type parameter without affecting their WIT representation.
+
+use crate::{Layout, WitType};
+use core::marker::PhantomData;
+
+impl<T: ?Sized> WitType for PhantomData<T> {
+    const SIZE: u32 = 0;
+    type Layout = Self;
+}
+
+impl<T: ?Sized> Layout for PhantomData<T> {
+    const ALIGNMENT: u32 = 1;
+}
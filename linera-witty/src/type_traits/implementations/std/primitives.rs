@@ -0,0 +1,33 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`] implementations for `bool`, `char` and `()`.
+
+use crate::{Layout, WitType};
+
+impl WitType for bool {
+    const SIZE: u32 = 1;
+    type Layout = Self;
+}
+
+impl Layout for bool {
+    const ALIGNMENT: u32 = 1;
+}
+
+impl WitType for char {
+    const SIZE: u32 = 4;
+    type Layout = Self;
+}
+
+impl Layout for char {
+    const ALIGNMENT: u32 = 4;
+}
+
+impl WitType for () {
+    const SIZE: u32 = 0;
+    type Layout = Self;
+}
+
+impl Layout for () {
+    const ALIGNMENT: u32 = 1;
+}
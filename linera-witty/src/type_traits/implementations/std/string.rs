@@ -0,0 +1,21 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`] implementation for owned UTF-8 strings.
+//!
+//! A WIT `string` lowers to a pointer/length pair, so this only needs `alloc::string::String` and
+//! never touches anything OS-specific — it works the same whether or not the `std` feature is
+//! enabled.
+
+use crate::{Layout, WitType};
+use alloc::string::String;
+
+impl WitType for String {
+    /// A pointer and a length, each a 32-bit value.
+    const SIZE: u32 = 8;
+    type Layout = Self;
+}
+
+impl Layout for String {
+    const ALIGNMENT: u32 = 4;
+}
@@ -0,0 +1,58 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`] implementations for tuples, whose WIT representation is a record of their
+//! elements in declaration order.
+
+use crate::{Layout, WitType};
+
+const fn align_up(offset: u32, alignment: u32) -> u32 {
+    (offset + alignment - 1) & !(alignment - 1)
+}
+
+const fn max(left: u32, right: u32) -> u32 {
+    if left > right {
+        left
+    } else {
+        right
+    }
+}
+
+macro_rules! impl_tuple {
+    ($($field:ident),+) => {
+        impl<$($field: WitType),+> WitType for ($($field,)+) {
+            const SIZE: u32 = {
+                let mut offset = 0u32;
+                $(
+                    offset = align_up(offset, <$field::Layout as Layout>::ALIGNMENT);
+                    offset += $field::SIZE;
+                )+
+                offset
+            };
+
+            type Layout = Self;
+        }
+
+        impl<$($field: WitType),+> Layout for ($($field,)+) {
+            const ALIGNMENT: u32 = {
+                let mut alignment = 1u32;
+                $(
+                    alignment = max(alignment, <$field::Layout as Layout>::ALIGNMENT);
+                )+
+                alignment
+            };
+        }
+    };
+}
+
+impl_tuple!(A);
+impl_tuple!(A, B);
+impl_tuple!(A, B, C);
+impl_tuple!(A, B, C, D);
+
+#[test]
+fn test_tuple_layout_inserts_padding_between_misaligned_fields() {
+    // `u8` (1 byte) followed by `u32` (4-byte aligned) needs 3 bytes of padding before the `u32`.
+    assert_eq!(<(u8, u32) as WitType>::SIZE, 8);
+    assert_eq!(<(u8, u32) as Layout>::ALIGNMENT, 4);
+}
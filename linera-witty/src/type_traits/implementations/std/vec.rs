@@ -0,0 +1,20 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! [`WitType`] implementation for owned lists.
+//!
+//! Like [`String`](super::string), a WIT `list<T>` lowers to a pointer/length pair, so this only
+//! needs `alloc::vec::Vec` and is available regardless of the `std` feature.
+
+use crate::{Layout, WitType};
+use alloc::vec::Vec;
+
+impl<T> WitType for Vec<T> {
+    /// A pointer and a length, each a 32-bit value.
+    const SIZE: u32 = 8;
+    type Layout = Self;
+}
+
+impl<T> Layout for Vec<T> {
+    const ALIGNMENT: u32 = 4;
+}
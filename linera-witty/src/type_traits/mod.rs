@@ -0,0 +1,22 @@
+// Copyright (c) Zefchain Labs, Inc.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Traits describing how Rust types map onto WIT (WebAssembly Interface Type) values, plus their
+//! implementations for common types.
+
+mod implementations;
+
+/// The in-memory layout of a WIT type.
+pub trait Layout {
+    /// The alignment required for a value of this layout, in bytes.
+    const ALIGNMENT: u32;
+}
+
+/// A Rust type that can be marshalled to and from its flattened WIT representation.
+pub trait WitType {
+    /// The size in bytes of this type's flattened in-memory representation.
+    const SIZE: u32;
+
+    /// This type's [`Layout`], used to compute padding when it's nested inside compound types.
+    type Layout: Layout;
+}